@@ -1,38 +1,149 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::error;
+use std::fmt;
 use std::fs;
 use std::io;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::Path;
 use std::str::from_utf8;
+use std::sync::OnceLock;
 
 // metadata section delimiter - xABxCDxEFMaxMind.com
 const METADATA_DELIMETER: [u8; 14] = [
     0xAB, 0xCD, 0xEF, 0x4D, 0x61, 0x78, 0x4D, 0x69, 0x6E, 0x64, 0x2E, 0x63, 0x6F, 0x6D,
 ];
 
-struct Metadata {
-    node_count: u64,
-    record_size: u64,
+/// Errors that can occur while opening a database or decoding a record.
+///
+/// Kept deliberately coarse (a variant plus a message) so malformed or
+/// truncated `.mmdb` files surface as a recoverable `Err` instead of a
+/// panic, which matters when reading databases we don't fully trust.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MaxMindDBError {
+    InvalidDatabase(String),
+    Io(String),
+    Decoding(String),
+    AddressNotFound(String),
+}
+
+impl fmt::Display for MaxMindDBError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaxMindDBError::InvalidDatabase(msg) => write!(f, "invalid database: {}", msg),
+            MaxMindDBError::Io(msg) => write!(f, "io error: {}", msg),
+            MaxMindDBError::Decoding(msg) => write!(f, "decoding error: {}", msg),
+            MaxMindDBError::AddressNotFound(msg) => write!(f, "address not found: {}", msg),
+        }
+    }
+}
+
+impl error::Error for MaxMindDBError {}
+
+impl From<io::Error> for MaxMindDBError {
+    fn from(err: io::Error) -> Self {
+        MaxMindDBError::Io(err.to_string())
+    }
+}
+
+/// The full metadata section of a `.mmdb` file: both the fields the search
+/// tree can't be read without (`node_count`, `record_size`, `ip_version`)
+/// and the descriptive ones callers use to identify what they loaded
+/// (`database_type`, `build_epoch`, ...).
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    pub node_count: u64,
+    pub record_size: u64,
+    pub ip_version: u64,
+    pub database_type: String,
+    pub languages: Vec<String>,
+    pub binary_format_major_version: u64,
+    pub binary_format_minor_version: u64,
+    pub build_epoch: u64,
+    pub description: HashMap<String, String>,
 }
 
 impl Metadata {
-    fn parse_metadata(buffer: &[u8]) -> Metadata {
-        let offset = Metadata::get_metadata_block_offset(&buffer);
+    fn parse_metadata(buffer: &[u8]) -> Result<Metadata, MaxMindDBError> {
+        let offset = Metadata::get_metadata_block_offset(buffer).ok_or_else(|| {
+            MaxMindDBError::InvalidDatabase("could not find metadata section".to_string())
+        })?;
         let mut decoder = Decoder::new(&buffer[offset..], 0);
 
-        let fields = vec!["node_count", "record_size", "ip_version"];
-        let metadata = decoder.decode_map(&fields);
+        let map = match decoder.decode_any()? {
+            Value::Map(map) => map,
+            _ => {
+                return Err(MaxMindDBError::InvalidDatabase(
+                    "metadata section is not a map".to_string(),
+                ))
+            }
+        };
+
+        let node_count = Metadata::require_u64(&map, "node_count")?;
+        let record_size = Metadata::require_u64(&map, "record_size")?;
+        let ip_version = Metadata::require_u64(&map, "ip_version")?;
+        let database_type = Metadata::require_string(&map, "database_type")?;
+        let binary_format_major_version =
+            Metadata::require_u64(&map, "binary_format_major_version")?;
+        let binary_format_minor_version =
+            Metadata::require_u64(&map, "binary_format_minor_version")?;
+        let build_epoch = Metadata::require_u64(&map, "build_epoch")?;
 
-        Metadata {
-            node_count: metadata["node_count"],
-            record_size: metadata["record_size"],
+        let languages = match map.get("languages") {
+            Some(Value::Array(values)) => values.iter().filter_map(Metadata::as_string).collect(),
+            _ => Vec::new(),
+        };
+        let description = match map.get("description") {
+            Some(Value::Map(entries)) => entries
+                .iter()
+                .filter_map(|(key, value)| Metadata::as_string(value).map(|v| (key.clone(), v)))
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        Ok(Metadata {
+            node_count,
+            record_size,
+            ip_version,
+            database_type,
+            languages,
+            binary_format_major_version,
+            binary_format_minor_version,
+            build_epoch,
+            description,
+        })
+    }
+
+    fn require_u64(map: &HashMap<String, Value>, field: &str) -> Result<u64, MaxMindDBError> {
+        match map.get(field) {
+            Some(Value::Uint(value)) => Ok(*value),
+            _ => Err(MaxMindDBError::InvalidDatabase(format!(
+                "metadata is missing {}",
+                field
+            ))),
+        }
+    }
+
+    fn require_string(map: &HashMap<String, Value>, field: &str) -> Result<String, MaxMindDBError> {
+        match map.get(field) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            _ => Err(MaxMindDBError::InvalidDatabase(format!(
+                "metadata is missing {}",
+                field
+            ))),
         }
     }
 
-    fn get_metadata_block_offset(buffer: &[u8]) -> usize {
+    fn as_string(value: &Value) -> Option<String> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    fn get_metadata_block_offset(buffer: &[u8]) -> Option<usize> {
         let mut current_offset = 13;
-        let mut offset = 0;
 
         for (i, &item) in buffer.iter().rev().enumerate() {
             if METADATA_DELIMETER[current_offset] == item {
@@ -42,11 +153,10 @@ impl Metadata {
             }
 
             if current_offset == 0 {
-                offset = buffer.len() - i - 2 + METADATA_DELIMETER.len();
-                break;
+                return Some(buffer.len() - i - 2 + METADATA_DELIMETER.len());
             }
         }
-        offset
+        None
     }
 }
 
@@ -79,6 +189,23 @@ pub enum ResultValue {
     Float(f32),
 }
 
+/// A fully self-describing data record, for callers who don't know the
+/// schema ahead of time and want the whole tree (e.g. dumping a record for
+/// an IP). `Uint16`/`Uint32`/`Uint64` all collapse into `Uint(u64)`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Uint(u64),
+    Int32(i32),
+    Uint128(u128),
+    Boolean(bool),
+    Double(f64),
+    Float(f32),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Map(HashMap<String, Value>),
+}
+
 struct Decoder<'a> {
     buffer: &'a [u8],
     offset: usize,
@@ -93,26 +220,39 @@ impl<'a> Decoder<'a> {
         self.offset += n
     }
 
-    fn current_byte(&mut self) -> u8 {
-        self.move_caret(1);
-        self.buffer[self.offset - 1]
+    /// The error for when `buffer` is truncated or a length/offset decoded
+    /// from it would run past the end — i.e. the file is too short to back
+    /// up what its own control bytes claim.
+    fn truncated() -> MaxMindDBError {
+        MaxMindDBError::Decoding("unexpected end of database".to_string())
     }
 
-    fn current_byte_u64(&mut self) -> u64 {
+    fn current_byte(&mut self) -> Result<u8, MaxMindDBError> {
+        let byte = *self.buffer.get(self.offset).ok_or_else(Self::truncated)?;
         self.move_caret(1);
-        self.buffer[self.offset - 1] as u64
+        Ok(byte)
     }
 
-    fn next_bytes(&mut self, size: usize) -> &[u8] {
-        self.move_caret(size);
-        &self.buffer[self.offset - size..self.offset]
+    fn current_byte_u64(&mut self) -> Result<u64, MaxMindDBError> {
+        Ok(self.current_byte()? as u64)
     }
 
-    fn decode_ctrl_byte(&mut self) -> (Type, usize) {
-        let byte = self.current_byte();
+    fn next_bytes(&mut self, size: usize) -> Result<&[u8], MaxMindDBError> {
+        let end = self
+            .offset
+            .checked_add(size)
+            .filter(|&end| end <= self.buffer.len())
+            .ok_or_else(Self::truncated)?;
+        let data = &self.buffer[self.offset..end];
+        self.offset = end;
+        Ok(data)
+    }
+
+    fn decode_ctrl_byte(&mut self) -> Result<(Type, usize), MaxMindDBError> {
+        let byte = self.current_byte()?;
         let mut type_bits = byte >> 5;
         if type_bits == 0 {
-            type_bits = 7 + self.current_byte();
+            type_bits = 7 + self.current_byte()?;
         }
         let data_type = match type_bits {
             0 => Type::Extended,
@@ -131,31 +271,32 @@ impl<'a> Decoder<'a> {
             13 => Type::EndMarker,
             14 => Type::Boolean,
             15 => Type::Float,
-            _ => unreachable!(),
+            other => {
+                return Err(MaxMindDBError::Decoding(format!(
+                    "unknown control byte type {}",
+                    other
+                )))
+            }
         };
         let size = match byte & 0x1F {
             size if size < 29 => size as u64,
-            29 => 29 + self.decode_n_bytes_as_uint(1),
-            30 => 285 + self.decode_n_bytes_as_uint(2),
-            31 => 65821 + self.decode_n_bytes_as_uint(3),
+            29 => 29 + self.decode_n_bytes_as_uint(1)?,
+            30 => 285 + self.decode_n_bytes_as_uint(2)?,
+            31 => 65821 + self.decode_n_bytes_as_uint(3)?,
             _ => unreachable!(),
         } as usize;
-        (data_type, size)
+        Ok((data_type, size))
     }
 
-    fn decode_n_bytes_as_uint(&mut self, n: usize) -> u64 {
-        self.next_bytes(n)
+    fn decode_n_bytes_as_uint(&mut self, n: usize) -> Result<u64, MaxMindDBError> {
+        Ok(self
+            .next_bytes(n)?
             .iter()
-            .fold(0u64, |acc, &x| (acc << 8) | u64::from(x))
-    }
-
-    fn decode_uint(&mut self) -> u64 {
-        let (_, size) = self.decode_ctrl_byte();
-        self.decode_n_bytes_as_uint(size)
+            .fold(0u64, |acc, &x| (acc << 8) | u64::from(x)))
     }
 
-    fn skip_value(&mut self) {
-        let (data_type, size) = self.decode_ctrl_byte();
+    fn skip_value(&mut self) -> Result<(), MaxMindDBError> {
+        let (data_type, size) = self.decode_ctrl_byte()?;
         match data_type {
             Type::String
             | Type::Double
@@ -169,41 +310,44 @@ impl<'a> Decoder<'a> {
             }
             Type::Pointer => {
                 // as a side effect of pointer resolving we'll move the carret
-                self.get_pointer_address();
+                self.get_pointer_address()?;
             }
             Type::Array => {
                 for _ in 0..size {
-                    self.skip_value();
+                    self.skip_value()?;
                 }
             }
             Type::Map => {
                 for _ in 0..size {
-                    self.skip_value();
-                    self.skip_value();
+                    self.skip_value()?;
+                    self.skip_value()?;
                 }
             }
             Type::Boolean => {}
-            _ => unreachable!(),
+            other => {
+                return Err(MaxMindDBError::Decoding(format!(
+                    "cannot skip value of type {:?}",
+                    other
+                )))
+            }
         }
+        Ok(())
     }
 
     fn decode_map_recursively(
         &mut self,
         fields: &[&str],
         result: &mut HashMap<String, ResultValue>,
-    ) -> Option<()> {
+    ) -> Result<(), MaxMindDBError> {
         // while decoding map, we store initial offset of the map, to be able start search
         // from scratch for every field
         let map_offset = self.offset;
-        let mut has_found = None;
 
         for &field in fields.iter() {
             self.offset = map_offset;
-            if self.find_field(field, field, result) {
-                has_found = Some(());
-            }
+            self.find_field(field, field, result)?;
         }
-        has_found
+        Ok(())
     }
 
     fn find_field(
@@ -211,10 +355,11 @@ impl<'a> Decoder<'a> {
         field: &str,
         parts: &str,
         result: &mut HashMap<String, ResultValue>,
-    ) -> bool {
+    ) -> Result<bool, MaxMindDBError> {
         if parts.is_empty() {
-            result.insert(String::from(field), self.decode_value());
-            return true;
+            let value = self.decode_value()?;
+            result.insert(String::from(field), value);
+            return Ok(true);
         }
         let dot_index = match parts.find('.') {
             Some(value) => value,
@@ -231,10 +376,10 @@ impl<'a> Decoder<'a> {
             Err(_) => (false, 0),
         };
 
-        let size = match self.decode_ctrl_byte() {
+        let size = match self.decode_ctrl_byte()? {
             (Type::Pointer, _) => {
-                self.offset = self.get_pointer_address();
-                let (_, size) = self.decode_ctrl_byte();
+                self.offset = self.get_pointer_address()?;
+                let (_, size) = self.decode_ctrl_byte()?;
                 size
             }
             (_, size) => size,
@@ -246,184 +391,366 @@ impl<'a> Decoder<'a> {
                     return self.find_field(field, next_parts, result);
                 }
             } else {
-                let key = self.decode_string();
+                let key = self.decode_string()?;
                 if key == search_for {
                     return self.find_field(field, next_parts, result);
                 }
             }
-            self.skip_value()
+            self.skip_value()?
         }
-        false
+        Ok(false)
     }
 
-    fn decode_value(&mut self) -> ResultValue {
-        let (data_type, size) = self.decode_ctrl_byte();
+    fn decode_value(&mut self) -> Result<ResultValue, MaxMindDBError> {
+        let (data_type, size) = self.decode_ctrl_byte()?;
         match data_type {
             Type::String => {
-                let value = from_utf8(self.next_bytes(size)).unwrap();
-                ResultValue::String(String::from(value))
+                let value = from_utf8(self.next_bytes(size)?).map_err(|e| {
+                    MaxMindDBError::Decoding(format!("invalid utf-8 string: {}", e))
+                })?;
+                Ok(ResultValue::String(String::from(value)))
             }
             Type::Pointer => {
-                self.offset = self.get_pointer_address();
+                self.offset = self.get_pointer_address()?;
                 self.decode_value()
             }
-            Type::Boolean => ResultValue::Boolean(size == 1),
+            Type::Boolean => Ok(ResultValue::Boolean(size == 1)),
             Type::Float => {
                 let raw_value: u32 = self
-                    .next_bytes(size)
+                    .next_bytes(size)?
                     .iter()
                     .fold(0u32, |acc, &x| (acc << 8) | u32::from(x));
                 let value = f32::from_bits(raw_value);
-                ResultValue::Float(value)
+                Ok(ResultValue::Float(value))
             }
             Type::Double => {
-                let value = f64::from_bits(self.decode_n_bytes_as_uint(size));
-                ResultValue::Double(value)
+                let value = f64::from_bits(self.decode_n_bytes_as_uint(size)?);
+                Ok(ResultValue::Double(value))
             }
-            _ => unimplemented!(),
+            other => Err(MaxMindDBError::Decoding(format!(
+                "decoding of type {:?} is not implemented yet",
+                other
+            ))),
         }
     }
 
-    pub fn decode_map(&mut self, fields: &[&str]) -> HashMap<String, u64> {
-        let mut result: HashMap<String, u64> = HashMap::with_capacity(fields.len());
-
-        let (_, size) = self.decode_ctrl_byte();
-        for _ in 0..size {
-            let key = self.decode_string();
-            if fields.contains(&key) {
-                result.insert(String::from(key), self.decode_uint());
-            } else {
-                self.skip_value()
+    /// Decode a whole value tree rooted at the current offset, following
+    /// pointers and recursing into maps/arrays as needed.
+    fn decode_any(&mut self) -> Result<Value, MaxMindDBError> {
+        let (data_type, size) = self.decode_ctrl_byte()?;
+        match data_type {
+            Type::Pointer => {
+                let target = self.get_pointer_address()?;
+                // resume right after the pointer, not after the pointed-to value
+                let after_pointer = self.offset;
+                self.offset = target;
+                let value = self.decode_any()?;
+                self.offset = after_pointer;
+                Ok(value)
+            }
+            Type::String => {
+                let value = from_utf8(self.next_bytes(size)?).map_err(|e| {
+                    MaxMindDBError::Decoding(format!("invalid utf-8 string: {}", e))
+                })?;
+                Ok(Value::String(String::from(value)))
+            }
+            Type::Boolean => Ok(Value::Boolean(size == 1)),
+            Type::Float => {
+                let raw_value: u32 = self
+                    .next_bytes(size)?
+                    .iter()
+                    .fold(0u32, |acc, &x| (acc << 8) | u32::from(x));
+                Ok(Value::Float(f32::from_bits(raw_value)))
+            }
+            Type::Double => Ok(Value::Double(f64::from_bits(self.decode_n_bytes_as_uint(size)?))),
+            Type::Bytes => Ok(Value::Bytes(self.next_bytes(size)?.to_vec())),
+            Type::Uint16 | Type::Uint32 | Type::Uint64 => {
+                Ok(Value::Uint(self.decode_n_bytes_as_uint(size)?))
+            }
+            Type::Int32 => Ok(Value::Int32(self.decode_n_bytes_as_uint(size)? as i32)),
+            Type::Uint128 => {
+                let value = self
+                    .next_bytes(size)?
+                    .iter()
+                    .fold(0u128, |acc, &x| (acc << 8) | u128::from(x));
+                Ok(Value::Uint128(value))
+            }
+            Type::Array => {
+                let mut values = Vec::with_capacity(size);
+                for _ in 0..size {
+                    values.push(self.decode_any()?);
+                }
+                Ok(Value::Array(values))
             }
+            Type::Map => {
+                let mut map = HashMap::with_capacity(size);
+                for _ in 0..size {
+                    let key = self.decode_string()?.to_string();
+                    let value = self.decode_any()?;
+                    map.insert(key, value);
+                }
+                Ok(Value::Map(map))
+            }
+            other => Err(MaxMindDBError::Decoding(format!(
+                "unexpected type {:?} while decoding value tree",
+                other
+            ))),
         }
-        result
     }
 
-    fn decode_string(&mut self) -> &str {
-        let (data_type, size) = self.decode_ctrl_byte();
+    fn decode_string(&mut self) -> Result<&str, MaxMindDBError> {
+        let (data_type, size) = self.decode_ctrl_byte()?;
         match data_type {
             Type::String => {
-                let data = self.next_bytes(size);
-                from_utf8(data).unwrap()
+                let data = self.next_bytes(size)?;
+                from_utf8(data)
+                    .map_err(|e| MaxMindDBError::Decoding(format!("invalid utf-8 string: {}", e)))
             }
             Type::Pointer => {
-                let pointer_offset = self.get_pointer_address();
-                let byte = &self.buffer[pointer_offset];
+                let pointer_offset = self.get_pointer_address()?;
+                let byte = *self
+                    .buffer
+                    .get(pointer_offset)
+                    .ok_or_else(Self::truncated)?;
                 let size = match byte & 0x1F {
                     size if size < 29 => size as u64,
-                    29 => 29 + self.decode_n_bytes_as_uint(1),
-                    30 => 285 + self.decode_n_bytes_as_uint(2),
-                    31 => 65821 + self.decode_n_bytes_as_uint(3),
-                    _ => panic!("unreachable"),
+                    29 => 29 + self.decode_n_bytes_as_uint(1)?,
+                    30 => 285 + self.decode_n_bytes_as_uint(2)?,
+                    31 => 65821 + self.decode_n_bytes_as_uint(3)?,
+                    _ => unreachable!(),
                 } as usize;
 
                 let left_bound = pointer_offset + 1;
-                let data = &self.buffer[left_bound..left_bound + size];
-                let parsed = from_utf8(data);
-                parsed.expect("found invalid string")
+                let right_bound = left_bound.checked_add(size).ok_or_else(Self::truncated)?;
+                let data = self
+                    .buffer
+                    .get(left_bound..right_bound)
+                    .ok_or_else(Self::truncated)?;
+                from_utf8(data).map_err(|e| {
+                    MaxMindDBError::Decoding(format!("invalid utf-8 string at pointer: {}", e))
+                })
             }
-            _ => unreachable!("tried to decode string with wrong type {:?}", data_type),
+            other => Err(MaxMindDBError::Decoding(format!(
+                "tried to decode string with wrong type {:?}",
+                other
+            ))),
         }
     }
 
-    fn get_pointer_address(&mut self) -> usize {
-        let current_byte = self.buffer[self.offset - 1] as u64;
+    fn get_pointer_address(&mut self) -> Result<usize, MaxMindDBError> {
+        let current_byte = *self
+            .offset
+            .checked_sub(1)
+            .and_then(|i| self.buffer.get(i))
+            .ok_or_else(Self::truncated)? as u64;
         let size = match current_byte & 0x1F {
-            size if size < 29 => size as u64,
-            29 => 29 + self.decode_n_bytes_as_uint(1),
-            30 => 285 + self.decode_n_bytes_as_uint(2),
-            31 => 65821 + self.decode_n_bytes_as_uint(3),
+            size if size < 29 => size,
+            29 => 29 + self.decode_n_bytes_as_uint(1)?,
+            30 => 285 + self.decode_n_bytes_as_uint(2)?,
+            31 => 65821 + self.decode_n_bytes_as_uint(3)?,
             _ => unreachable!(),
         } as u64;
         let pointer_size = (size >> 3) & 0x3;
         let pointer_offset = match pointer_size {
-            0 => ((size & 0x7) << 8) + self.current_byte_u64(),
+            0 => ((size & 0x7) << 8) + self.current_byte_u64()?,
             1 => {
                 2048 + (((size & 0x7) << 16)
-                    | self.current_byte_u64() << 8
-                    | self.current_byte_u64())
+                    | self.current_byte_u64()? << 8
+                    | self.current_byte_u64()?)
             }
             2 => {
                 526336
                     + (((size & 0x7) << 24)
-                        | self.current_byte_u64() << 16
-                        | self.current_byte_u64() << 8
-                        | self.current_byte_u64())
+                        | self.current_byte_u64()? << 16
+                        | self.current_byte_u64()? << 8
+                        | self.current_byte_u64()?)
             }
             3 => {
-                self.current_byte_u64() << 24
-                    | self.current_byte_u64() << 16
-                    | self.current_byte_u64() << 8
-                    | self.current_byte_u64()
+                self.current_byte_u64()? << 24
+                    | self.current_byte_u64()? << 16
+                    | self.current_byte_u64()? << 8
+                    | self.current_byte_u64()?
             }
             _ => unreachable!("wrong pointer size"),
         };
-        pointer_offset as usize
+        Ok(pointer_offset as usize)
     }
 }
 
-pub struct Reader {
+/// A reader generic over its backing store `S`, which only needs to give us
+/// a byte slice view of the whole `.mmdb` file. This lets [`Reader::open`]
+/// keep the database in an owned `Vec<u8>` while [`Reader::open_mmap`] (with
+/// the `mmap` feature) shares one read-only memory mapping across threads
+/// instead of copying the file per reader.
+pub struct Reader<S: AsRef<[u8]> = Vec<u8>> {
     metadata: Metadata,
-    buffer: Vec<u8>,
+    buffer: S,
 }
 
-impl Reader {
-    pub fn open(filename: &str) -> io::Result<Reader> {
+impl Reader<Vec<u8>> {
+    pub fn open(filename: &str) -> Result<Reader<Vec<u8>>, MaxMindDBError> {
         let path = Path::new(filename);
-        let buffer: Vec<u8> = fs::read(&path)?;
-        let metadata = Metadata::parse_metadata(&buffer);
+        let buffer: Vec<u8> = fs::read(path)?;
+        let metadata = Metadata::parse_metadata(&buffer)?;
 
         Ok(Reader { metadata, buffer })
     }
+}
 
-    fn ip_to_bitmask(ip_address: IpAddr) -> (u32, usize) {
-        let closure = |acc, &x| (acc << 8) | u32::from(x);
-        let (bitmask, size) = match ip_address {
-            IpAddr::V4(ip) => (ip.octets().iter().fold(0, closure), 32),
-            IpAddr::V6(ip) => (ip.octets().iter().fold(0, closure), 128),
-        };
-        (bitmask, size)
+#[cfg(feature = "mmap")]
+impl Reader<memmap2::Mmap> {
+    /// Memory-map `filename` instead of reading it into an owned buffer, so
+    /// many worker threads can share a single read-only mapping of the file
+    /// with near-zero startup cost.
+    pub fn open_mmap(filename: &str) -> Result<Reader<memmap2::Mmap>, MaxMindDBError> {
+        let file = fs::File::open(filename)?;
+        let buffer = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| {
+            MaxMindDBError::Io(format!("failed to mmap {}: {}", filename, e))
+        })?;
+        let metadata = Metadata::parse_metadata(&buffer)?;
+
+        Ok(Reader { metadata, buffer })
+    }
+}
+
+impl<S: AsRef<[u8]>> Reader<S> {
+    /// Carries the address as a `u128` (instead of folding it into a `u32`)
+    /// so the full 128 bits of an IPv6 address survive the traversal.
+    fn ip_to_bitmask(ip_address: IpAddr) -> (u128, usize) {
+        match ip_address {
+            IpAddr::V4(ip) => (u128::from(u32::from(ip)), 32),
+            IpAddr::V6(ip) => (u128::from(ip), 128),
+        }
     }
 
-    fn find_ip_offset(&self, ip: IpAddr) -> Option<u64> {
+    /// The byte offset, within the search tree, of the node a lookup for
+    /// `ip` starts at. `None` means the tree can't represent this address
+    /// family at all (e.g. an IPv6 query against an `ip_version` 4 database).
+    ///
+    /// IPv4 addresses stored in an IPv6 tree live under the ::0.0.0.0/96
+    /// prefix; since that prefix is always zero-padding, the tree nodes for
+    /// it are laid out as a straight chain, so we can jump straight to node
+    /// 96 instead of walking 96 zero bits from the root.
+    fn root_offset(&self, ip: IpAddr) -> Option<usize> {
+        let node_size_in_bytes = (self.metadata.record_size / 4) as usize;
+        match (ip, self.metadata.ip_version) {
+            (IpAddr::V4(_), 6) => Some(96 * node_size_in_bytes),
+            (IpAddr::V4(_), _) => Some(0),
+            (IpAddr::V6(_), 6) => Some(0),
+            (IpAddr::V6(_), _) => None,
+        }
+    }
+
+    /// Read both records (bit=0 and bit=1) stored at `offset`.
+    fn read_node(&self, offset: usize) -> Result<(u64, u64), MaxMindDBError> {
+        let buffer = self.buffer.as_ref();
         let closure = |acc, &x| (acc << 8) | u64::from(x);
         let node_size_in_bytes = (self.metadata.record_size / 4) as usize;
+        let end = offset
+            .checked_add(node_size_in_bytes)
+            .filter(|&end| end <= buffer.len())
+            .ok_or_else(|| MaxMindDBError::Decoding("search tree node out of bounds".to_string()))?;
+        let node = &buffer[offset..end];
 
-        let (bitmask, size) = Reader::ip_to_bitmask(ip);
-        let mut offset = match ip {
-            IpAddr::V4(_) => 96 * node_size_in_bytes,
-            IpAddr::V6(_) => 0,
+        // TODO let's make record_size enum
+        match self.metadata.record_size {
+            28 => {
+                // The middle byte's high nibble extends the left record,
+                // the low nibble extends the right one.
+                let middle_byte = node[3] as u64;
+                let left = node[..3].iter().fold(middle_byte >> 4, closure);
+                let right = node[4..].iter().fold(middle_byte & 0x0f, closure);
+                Ok((left, right))
+            }
+            _ => {
+                let half: usize = node_size_in_bytes / 2;
+                let left = node[..half].iter().fold(0, closure);
+                let right = node[half..].iter().fold(0, closure);
+                Ok((left, right))
+            }
+        }
+    }
+
+    fn find_ip_offset(&self, ip: IpAddr) -> Result<Option<u64>, MaxMindDBError> {
+        Ok(self
+            .find_ip_offset_with_prefix(ip)?
+            .map(|(offset, _)| offset))
+    }
+
+    /// Like [`find_ip_offset`](Self::find_ip_offset), but also returns the
+    /// length (in bits) of the matched network prefix, i.e. how deep the
+    /// bit-by-bit traversal went before it hit a terminal record.
+    fn find_ip_offset_with_prefix(
+        &self,
+        ip: IpAddr,
+    ) -> Result<Option<(u64, usize)>, MaxMindDBError> {
+        let node_size_in_bytes = (self.metadata.record_size / 4) as usize;
+        let (bitmask, size) = Reader::<S>::ip_to_bitmask(ip);
+        let mut offset = match self.root_offset(ip) {
+            Some(offset) => offset,
+            None => return Ok(None),
         };
 
         for i in (0..size).rev() {
             let is_left = (bitmask >> i) & 1 == 0;
-            let node = &self.buffer[offset..offset + node_size_in_bytes];
-
-            // TODO let's make record_size enum
-            let calculated_value = match self.metadata.record_size {
-                28 => {
-                    let middle_byte = self.buffer[offset + 3] as u64;
-                    match is_left {
-                        true => node[..3].iter().fold(middle_byte, closure),
-                        false => node[4..].iter().fold(middle_byte, closure),
-                    }
-                }
-                _ => {
-                    let half: usize = node_size_in_bytes / 2;
-                    match is_left {
-                        true => node[..half].iter().fold(0, closure),
-                        false => node[half..].iter().fold(0, closure),
-                    }
-                }
-            };
+            let (left, right) = self.read_node(offset)?;
+            let calculated_value = if is_left { left } else { right };
 
             match calculated_value.cmp(&self.metadata.node_count) {
                 Ordering::Equal => break,
                 Ordering::Less => offset = calculated_value as usize * node_size_in_bytes,
-                _ => return Some(calculated_value),
+                _ => return Ok(Some((calculated_value, size - i))),
             };
         }
-        None
+        Ok(None)
+    }
+
+    /// Zero out every bit of `ip` past `prefix_len`, giving the canonical
+    /// network base address for a matched record.
+    fn network_address(ip: IpAddr, prefix_len: usize) -> IpAddr {
+        match ip {
+            IpAddr::V4(v4) => {
+                let octets = Reader::<S>::mask_octets(&v4.octets(), prefix_len);
+                IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+            }
+            IpAddr::V6(v6) => {
+                let octets = Reader::<S>::mask_octets(&v6.octets(), prefix_len);
+                let mut arr = [0u8; 16];
+                arr.copy_from_slice(&octets);
+                IpAddr::V6(Ipv6Addr::from(arr))
+            }
+        }
+    }
+
+    fn mask_octets(octets: &[u8], prefix_len: usize) -> Vec<u8> {
+        octets
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| {
+                let bit_offset = i * 8;
+                if bit_offset >= prefix_len {
+                    0
+                } else if bit_offset + 8 <= prefix_len {
+                    byte
+                } else {
+                    let keep_bits = prefix_len - bit_offset;
+                    byte & (!0u8 << (8 - keep_bits))
+                }
+            })
+            .collect()
+    }
+
+    fn decoder_at(&self, ip: IpAddr) -> Result<Decoder<'_>, MaxMindDBError> {
+        let search_tree_size = (self.metadata.record_size / 4) * self.metadata.node_count + 16;
+        let offset = self
+            .find_ip_offset(ip)?
+            .ok_or_else(|| MaxMindDBError::AddressNotFound(ip.to_string()))?;
+        let data_section_offset = offset - self.metadata.node_count - 16;
+
+        Ok(Decoder::new(
+            &self.buffer.as_ref()[search_tree_size as usize..],
+            data_section_offset as usize,
+        ))
     }
 
     pub fn lookup(
@@ -431,17 +758,253 @@ impl Reader {
         ip: IpAddr,
         fields: &[&str],
         result: &mut HashMap<String, ResultValue>,
-    ) -> Option<()> {
+    ) -> Result<(), MaxMindDBError> {
+        self.decoder_at(ip)?.decode_map_recursively(fields, result)
+    }
+
+    /// Decode the complete data record for `ip` into a self-describing
+    /// [`Value`] tree, for callers who don't know the schema ahead of time.
+    pub fn lookup_record(&self, ip: IpAddr) -> Result<Value, MaxMindDBError> {
+        self.decoder_at(ip)?.decode_any()
+    }
+
+    /// The database's metadata section: `database_type`, `build_epoch`,
+    /// `ip_version` and the rest, so callers can tell which product/vintage
+    /// they loaded before relying on its lookup behavior.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Like [`lookup`](Self::lookup), but also returns the matched network
+    /// (base address + prefix length) so callers can cache the result for
+    /// the whole block instead of re-querying adjacent addresses.
+    pub fn lookup_prefix(
+        &self,
+        ip: IpAddr,
+        fields: &[&str],
+        result: &mut HashMap<String, ResultValue>,
+    ) -> Result<(IpAddr, u8), MaxMindDBError> {
         let search_tree_size = (self.metadata.record_size / 4) * self.metadata.node_count + 16;
-        let offset = self.find_ip_offset(ip)?;
+        let (offset, prefix_len) = self
+            .find_ip_offset_with_prefix(ip)?
+            .ok_or_else(|| MaxMindDBError::AddressNotFound(ip.to_string()))?;
         let data_section_offset = offset - self.metadata.node_count - 16;
 
         let mut decoder = Decoder::new(
-            &self.buffer[search_tree_size as usize..],
+            &self.buffer.as_ref()[search_tree_size as usize..],
             data_section_offset as usize,
         );
-        decoder.decode_map_recursively(fields, result)
+        decoder.decode_map_recursively(fields, result)?;
+
+        Ok((
+            Reader::<S>::network_address(ip, prefix_len),
+            prefix_len as u8,
+        ))
+    }
+
+    /// Depth-first walk of the whole search tree, yielding every terminal
+    /// (network, record) pair stored in the database.
+    pub fn networks(&self) -> Networks<'_, S> {
+        let size = if self.metadata.ip_version == 4 { 32 } else { 128 };
+        Networks {
+            reader: self,
+            size,
+            stack: vec![(0, 0, 0)],
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Like [`networks`](Self::networks), but restricted to the networks
+    /// contained within `network/prefix_len`.
+    pub fn within(&self, network: IpAddr, prefix_len: u8) -> Result<Networks<'_, S>, MaxMindDBError> {
+        let node_size_in_bytes = (self.metadata.record_size / 4) as usize;
+        let (bitmask, size) = Reader::<S>::ip_to_bitmask(network);
+        if prefix_len as usize > size {
+            return Err(MaxMindDBError::Decoding(format!(
+                "prefix length {} is longer than a {}-bit address",
+                prefix_len, size
+            )));
+        }
+
+        let mut offset = self
+            .root_offset(network)
+            .ok_or_else(|| MaxMindDBError::AddressNotFound(network.to_string()))?;
+        let mut bits: u128 = 0;
+        let mut depth = 0usize;
+
+        while depth < prefix_len as usize {
+            let i = size - 1 - depth;
+            let is_left = (bitmask >> i) & 1 == 0;
+            let (left, right) = self.read_node(offset)?;
+            let value = if is_left { left } else { right };
+            bits = (bits << 1) | u128::from(!is_left);
+            depth += 1;
+
+            match value.cmp(&self.metadata.node_count) {
+                // nothing is stored anywhere under this network
+                Ordering::Equal => {
+                    return Ok(Networks {
+                        reader: self,
+                        size,
+                        stack: Vec::new(),
+                        pending: VecDeque::new(),
+                    })
+                }
+                Ordering::Less => offset = value as usize * node_size_in_bytes,
+                // The whole network maps to a single, less specific record.
+                // `within` must never yield anything broader than what was
+                // asked for, so clamp the emitted network to `prefix_len`
+                // (the query's own bits) rather than the shallower depth
+                // the stored record actually lives at.
+                Ordering::Greater => {
+                    let query_bits = if prefix_len == 0 {
+                        0
+                    } else {
+                        bitmask >> (size - prefix_len as usize)
+                    };
+                    let mut pending = VecDeque::new();
+                    pending.push_back((query_bits, prefix_len as usize, value));
+                    return Ok(Networks {
+                        reader: self,
+                        size,
+                        stack: Vec::new(),
+                        pending,
+                    });
+                }
+            }
+        }
+
+        Ok(Networks {
+            reader: self,
+            size,
+            stack: vec![(offset, bits, depth)],
+            pending: VecDeque::new(),
+        })
+    }
+}
+
+/// Iterator over `(network, prefix_len, record)` triples produced by
+/// [`Reader::networks`] and [`Reader::within`]. Walks the binary search tree
+/// depth-first with an explicit stack instead of recursion.
+pub struct Networks<'r, S: AsRef<[u8]>> {
+    reader: &'r Reader<S>,
+    size: usize,
+    stack: Vec<(usize, u128, usize)>,
+    pending: VecDeque<(u128, usize, u64)>,
+}
+
+impl<'r, S: AsRef<[u8]>> Networks<'r, S> {
+    /// Turn accumulated traversal bits into `(network, prefix_len)`. IPv4
+    /// addresses stored in an IPv6 tree live entirely under the ::0.0.0.0/96
+    /// prefix, so a record whose path never leaves that subtree is reported
+    /// back as an `IpAddr::V4` with the 96 zero bits stripped from the
+    /// prefix length, instead of as a ::/120-ish IPv6 network.
+    fn network_from_bits(&self, bits: u128, depth: usize) -> (IpAddr, usize) {
+        if self.size == 128 && depth >= 96 && (bits >> (depth - 96)) == 0 {
+            let v4_depth = depth - 96;
+            let v4_bits = if v4_depth == 0 { 0 } else { (bits as u32) << (32 - v4_depth) };
+            return (IpAddr::V4(Ipv4Addr::from(v4_bits)), v4_depth);
+        }
+
+        let value: u128 = if depth == 0 { 0 } else { bits << (self.size - depth) };
+        if self.size == 32 {
+            (IpAddr::V4(Ipv4Addr::from(value as u32)), depth)
+        } else {
+            let mut arr = [0u8; 16];
+            arr.copy_from_slice(&value.to_be_bytes());
+            (IpAddr::V6(Ipv6Addr::from(arr)), depth)
+        }
+    }
+
+    fn emit(&self, bits: u128, depth: usize, value: u64) -> Result<(IpAddr, u8, Value), MaxMindDBError> {
+        let reader = self.reader;
+        let search_tree_size = (reader.metadata.record_size / 4) * reader.metadata.node_count + 16;
+        let data_section_offset = value - reader.metadata.node_count - 16;
+        let mut decoder = Decoder::new(
+            &reader.buffer.as_ref()[search_tree_size as usize..],
+            data_section_offset as usize,
+        );
+        let record = decoder.decode_any()?;
+        let (network, prefix_len) = self.network_from_bits(bits, depth);
+        Ok((network, prefix_len as u8, record))
+    }
+}
+
+impl<'r, S: AsRef<[u8]>> Iterator for Networks<'r, S> {
+    type Item = Result<(IpAddr, u8, Value), MaxMindDBError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((bits, depth, value)) = self.pending.pop_front() {
+                return Some(self.emit(bits, depth, value));
+            }
+
+            let (offset, bits, depth) = self.stack.pop()?;
+            let node_count = self.reader.metadata.node_count;
+            let node_size_in_bytes = (self.reader.metadata.record_size / 4) as usize;
+            let (left, right) = match self.reader.read_node(offset) {
+                Ok(pair) => pair,
+                Err(e) => return Some(Err(e)),
+            };
+
+            for (bit, child_value) in [(0u128, left), (1u128, right)] {
+                if depth >= self.size {
+                    continue;
+                }
+                let child_bits = (bits << 1) | bit;
+                let child_depth = depth + 1;
+                match child_value.cmp(&node_count) {
+                    Ordering::Equal => {}
+                    Ordering::Less => self
+                        .stack
+                        .push((child_value as usize * node_size_in_bytes, child_bits, child_depth)),
+                    Ordering::Greater => self.pending.push_back((child_bits, child_depth, child_value)),
+                }
+            }
+        }
+    }
+}
+
+static GLOBAL_READER: OnceLock<Reader<Vec<u8>>> = OnceLock::new();
+
+/// Initialize the process-global [`Reader`] from `path`, so every later
+/// [`search_by_ip`] call (from any thread) can borrow it without opening
+/// the database again. A no-op if the global reader is already
+/// initialized, explicitly or lazily via `MAXMINDDB_PATH`.
+pub fn init_global_reader(path: &str) -> Result<(), MaxMindDBError> {
+    if GLOBAL_READER.get().is_some() {
+        return Ok(());
+    }
+    let reader = Reader::open(path)?;
+    let _ = GLOBAL_READER.set(reader);
+    Ok(())
+}
+
+fn global_reader() -> Result<&'static Reader<Vec<u8>>, MaxMindDBError> {
+    if let Some(reader) = GLOBAL_READER.get() {
+        return Ok(reader);
     }
+    let path = env::var("MAXMINDDB_PATH").map_err(|_| {
+        MaxMindDBError::InvalidDatabase(
+            "global reader is not initialized: call init_global_reader() or set MAXMINDDB_PATH"
+                .to_string(),
+        )
+    })?;
+    init_global_reader(&path)?;
+    GLOBAL_READER
+        .get()
+        .ok_or_else(|| MaxMindDBError::InvalidDatabase("global reader init raced".to_string()))
+}
+
+/// Look up `ip` in the process-global reader, mirroring [`Reader::lookup`].
+/// Lazily opens the database from `MAXMINDDB_PATH` on first use if
+/// [`init_global_reader`] wasn't called explicitly.
+pub fn search_by_ip(
+    ip: IpAddr,
+    fields: &[&str],
+    result: &mut HashMap<String, ResultValue>,
+) -> Result<(), MaxMindDBError> {
+    global_reader()?.lookup(ip, fields, result)
 }
 
 #[cfg(test)]
@@ -456,7 +1019,7 @@ mod tests {
         let fields = vec!["city.names.en", "subdivisions.0.names.en"];
         let mut result: HashMap<String, ResultValue> = HashMap::with_capacity(fields.len());
 
-        assert!(reader.lookup(ip, &fields, &mut result).is_some());
+        reader.lookup(ip, &fields, &mut result).unwrap();
 
         let v = &result["subdivisions.0.names.en"];
         if let ResultValue::String(value) = v {
@@ -475,7 +1038,7 @@ mod tests {
         let reader = Reader::open("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
         let fields = vec!["location.latitude", "location.longitude"];
         let mut result: HashMap<String, ResultValue> = HashMap::with_capacity(fields.len());
-        assert!(reader.lookup(ip, &fields, &mut result).is_some());
+        reader.lookup(ip, &fields, &mut result).unwrap();
 
         if let ResultValue::Double(v) = result["location.latitude"] {
             assert_eq!(v, 51.514_2);
@@ -495,7 +1058,7 @@ mod tests {
             "country.is_in_european_union",
         ];
         let mut result: HashMap<String, ResultValue> = HashMap::with_capacity(fields.len());
-        assert!(reader.lookup(ip, &fields, &mut result).is_some());
+        reader.lookup(ip, &fields, &mut result).unwrap();
 
         let v = &result["country.names.en"];
         if let ResultValue::String(value) = v {
@@ -508,31 +1071,133 @@ mod tests {
         }
     }
 
+    #[test]
+    fn lookup_prefix_returns_matched_network() {
+        let ip: IpAddr = "81.2.69.160".parse().unwrap();
+        let reader = Reader::open("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+        let fields = vec!["country.names.en"];
+        let mut result: HashMap<String, ResultValue> = HashMap::with_capacity(fields.len());
+
+        let (network, prefix_len) = reader.lookup_prefix(ip, &fields, &mut result).unwrap();
+        assert_eq!(network, "81.2.69.0".parse::<IpAddr>().unwrap());
+        assert_eq!(prefix_len, 24);
+    }
+
+    #[test]
+    fn networks_contains_known_network() {
+        let reader = Reader::open("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+        let target: IpAddr = "81.2.69.0".parse().unwrap();
+
+        let found = reader
+            .networks()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .into_iter()
+            .any(|(network, prefix_len, _)| network == target && prefix_len == 24);
+        assert!(found);
+    }
+
+    #[test]
+    fn within_restricts_to_the_given_supernet() {
+        let reader = Reader::open("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+        let supernet: IpAddr = "81.2.69.0".parse().unwrap();
+
+        let networks = reader
+            .within(supernet, 24)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(!networks.is_empty());
+        for (network, prefix_len, _) in &networks {
+            assert!(*prefix_len >= 24);
+            let masked = match network {
+                IpAddr::V4(v4) => {
+                    let mask = !0u32 << (32 - 24);
+                    IpAddr::V4(std::net::Ipv4Addr::from(u32::from(*v4) & mask))
+                }
+                other => *other,
+            };
+            assert_eq!(masked, supernet);
+        }
+    }
+
+    #[test]
+    fn within_clamps_to_a_narrower_query_than_the_stored_record() {
+        let reader = Reader::open("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+        // 81.2.69.0/24 is stored as a single record (see
+        // `lookup_prefix_returns_matched_network`), so querying the /25 half
+        // of it must not hand back the whole /24.
+        let supernet: IpAddr = "81.2.69.128".parse().unwrap();
+
+        let networks = reader
+            .within(supernet, 25)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(networks.len(), 1);
+        let (network, prefix_len, _) = &networks[0];
+        assert_eq!(*network, supernet);
+        assert_eq!(*prefix_len, 25);
+    }
+
+    #[test]
+    fn global_reader_search_by_ip() {
+        init_global_reader("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+
+        let ip: IpAddr = "81.2.69.160".parse().unwrap();
+        let fields = vec!["city.names.en"];
+        let mut result: HashMap<String, ResultValue> = HashMap::with_capacity(fields.len());
+        search_by_ip(ip, &fields, &mut result).unwrap();
+
+        let v = &result["city.names.en"];
+        if let ResultValue::String(value) = v {
+            assert_eq!(value, &String::from("London"));
+        }
+    }
+
+    #[test]
+    fn lookup_address_not_found() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let reader = Reader::open("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
+        let fields = vec!["city.names.en"];
+        let mut result: HashMap<String, ResultValue> = HashMap::with_capacity(fields.len());
+
+        match reader.lookup(ip, &fields, &mut result) {
+            Err(MaxMindDBError::AddressNotFound(_)) => {}
+            other => panic!("expected AddressNotFound, got {:?}", other.is_ok()),
+        }
+    }
+
     #[test]
     fn metadata_parsing() {
         let reader = Reader::open("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
-        assert_eq!(reader.metadata.node_count, 1431);
-        assert_eq!(reader.metadata.record_size, 28);
+        let metadata = reader.metadata();
+        assert_eq!(metadata.node_count, 1431);
+        assert_eq!(metadata.record_size, 28);
+        assert_eq!(metadata.ip_version, 6);
+        assert_eq!(metadata.database_type, "GeoIP2-City");
+        assert!(metadata.languages.contains(&"en".to_string()));
+        assert!(metadata.description.contains_key("en"));
     }
 
     #[test]
     fn find_ip_offset() {
         let ip: IpAddr = "81.2.69.160".parse().unwrap();
         let reader = Reader::open("test_data/test-data/GeoIP2-City-Test.mmdb").unwrap();
-        let offset = reader.find_ip_offset(ip).unwrap();
+        let offset = reader.find_ip_offset(ip).unwrap().unwrap();
         assert_eq!(offset, 2589);
     }
 
     #[test]
     fn ip_bitmask() {
         let ip: IpAddr = "81.2.69.160".parse().unwrap();
-        let (bitmask, size) = Reader::ip_to_bitmask(ip);
+        let (bitmask, size) = Reader::<Vec<u8>>::ip_to_bitmask(ip);
         assert_eq!(bitmask, 1359103392);
         assert_eq!(size, 32);
-        // and ipv6
+        // and ipv6 - the full 128 bits must survive, not just the last 32
         let ip: IpAddr = "2001:0db8:85a3:0000:0000:8a2e:0370:7334".parse().unwrap();
-        let (bitmask, size) = Reader::ip_to_bitmask(ip);
-        assert_eq!(bitmask, 57701172);
+        let (bitmask, size) = Reader::<Vec<u8>>::ip_to_bitmask(ip);
+        assert_eq!(bitmask, 42_540_766_452_641_154_071_740_215_577_757_643_572);
         assert_eq!(size, 128);
     }
 }